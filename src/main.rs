@@ -1,14 +1,33 @@
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::SystemTime;
 use std::{
     fs::{self, ReadDir},
     path::{Path, PathBuf},
     vec,
 };
 
-use clap::Parser;
-use lopdf::{Bookmark, Document, Object, ObjectId};
+use clap::{Parser, ValueEnum};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Bookmark, Document, Object, ObjectId, Stream};
 
 const DEFAULT_FILE_NAME: &str = "merged.pdf";
+const MANIFEST_FILE_NAME: &str = "manifest.txt";
+
+/// How to order input documents before merging.
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq)]
+enum SortOrder {
+    /// Plain lexical filename order (the prior, default behavior).
+    Name,
+    /// Lexical order, but runs of digits are compared numerically, so
+    /// `slide2.pdf` sorts before `slide10.pdf`.
+    Natural,
+    /// Order by file modification time, oldest first.
+    Mtime,
+    /// Order by a `manifest.txt` file in the input directory, one relative
+    /// path per line; unlisted PDFs are appended afterward in natural order.
+    Manifest,
+}
 
 /// Merge PDF's in specified directory.
 #[derive(Parser, Debug)]
@@ -25,10 +44,59 @@ struct Args {
     /// Annotate file names to corner of first slides
     #[arg(default_value_t = false, short, long)]
     anno: bool,
+
+    /// Preserve each source document's own outline as nested bookmarks,
+    /// instead of one flat Page_N bookmark per document
+    #[arg(default_value_t = false, long)]
+    outlines: bool,
+
+    /// Prepend a clickable table-of-contents page linking to each document
+    #[arg(default_value_t = false, long)]
+    toc: bool,
+
+    /// How to order the input documents before merging
+    #[arg(long, value_enum, default_value = "name")]
+    sort: SortOrder,
+
+    /// Restrict which pages of a document are merged, as `FILE=1-3,7`;
+    /// repeatable, one occurrence per file
+    #[arg(long = "range", value_name = "FILE=PAGES")]
+    range: Vec<String>,
+
+    /// Pad each section to an even page count with a blank page, for
+    /// double-sided printing
+    #[arg(default_value_t = false, long)]
+    duplex: bool,
+}
+
+/// Parses `--range FILE=PAGES` specs (e.g. `"lecture1.pdf=1-3,7"`) into a
+/// map of file name to the set of 1-indexed page numbers to keep.
+fn parse_page_ranges(specs: &[String]) -> HashMap<String, BTreeSet<usize>> {
+    let mut ranges: HashMap<String, BTreeSet<usize>> = HashMap::new();
+
+    for spec in specs {
+        let Some((file_name, pages_spec)) = spec.split_once('=') else {
+            continue;
+        };
+        let entry = ranges.entry(file_name.to_string()).or_default();
+
+        for part in pages_spec.split(',') {
+            let part = part.trim();
+            if let Some((start, end)) = part.split_once('-') {
+                if let (Ok(start), Ok(end)) = (start.trim().parse::<usize>(), end.trim().parse::<usize>()) {
+                    entry.extend(start..=end);
+                }
+            } else if let Ok(page) = part.parse::<usize>() {
+                entry.insert(page);
+            }
+        }
+    }
+
+    ranges
 }
 
-fn load_documents_from_path(path: &PathBuf) -> Vec<(Document, String)> {
-    let mut docs: Vec<(Document, String)> = vec![];
+fn load_documents_from_path(path: &PathBuf) -> Vec<(Document, String, SystemTime)> {
+    let mut docs: Vec<(Document, String, SystemTime)> = vec![];
     let dir: ReadDir;
     match fs::read_dir(path) {
         Ok(v) => dir = v,
@@ -52,11 +120,14 @@ fn load_documents_from_path(path: &PathBuf) -> Vec<(Document, String)> {
             let file_name = file_name.to_str().unwrap_or("");
 
             if file_name.ends_with(".pdf") && file_name != DEFAULT_FILE_NAME {
-                let doc = Document::load(file_path);
+                let doc = Document::load(&file_path);
                 if doc.is_err() {
                     continue;
                 }
-                docs.push((doc.unwrap(), file_name.to_string()));
+                let mtime = fs::metadata(&file_path)
+                    .and_then(|meta| meta.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                docs.push((doc.unwrap(), file_name.to_string(), mtime));
             }
         } else if filetype.is_dir() {
             let recursive_docs = load_documents_from_path(&entry.path());
@@ -68,8 +139,388 @@ fn load_documents_from_path(path: &PathBuf) -> Vec<(Document, String)> {
     return docs;
 }
 
+/// Splits a filename into alternating runs of digits and non-digits, e.g.
+/// `"slide10.pdf"` -> `["slide", "10", ".pdf"]`.
+fn digit_runs(name: &str) -> Vec<&str> {
+    let mut runs = vec![];
+    let bytes = name.as_bytes();
+    let mut start = 0;
+
+    while start < bytes.len() {
+        let is_digit = bytes[start].is_ascii_digit();
+        let mut end = start + 1;
+        while end < bytes.len() && bytes[end].is_ascii_digit() == is_digit {
+            end += 1;
+        }
+        runs.push(&name[start..end]);
+        start = end;
+    }
+
+    runs
+}
+
+/// Natural-order comparator: digit runs compare by numeric value (ignoring
+/// leading zeros, falling back to run length then lexical order on ties),
+/// non-digit runs compare as byte strings.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (runs_a, runs_b) = (digit_runs(a), digit_runs(b));
+
+    for (run_a, run_b) in runs_a.iter().zip(runs_b.iter()) {
+        let both_numeric =
+            run_a.bytes().all(|c| c.is_ascii_digit()) && run_b.bytes().all(|c| c.is_ascii_digit());
+
+        let ordering = if both_numeric {
+            let trimmed_a = run_a.trim_start_matches('0');
+            let trimmed_b = run_b.trim_start_matches('0');
+            trimmed_a
+                .len()
+                .cmp(&trimmed_b.len())
+                .then_with(|| trimmed_a.cmp(trimmed_b))
+                .then_with(|| run_a.len().cmp(&run_b.len()))
+                .then_with(|| run_a.cmp(run_b))
+        } else {
+            run_a.cmp(run_b)
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    runs_a.len().cmp(&runs_b.len())
+}
+
+/// Reads `manifest.txt` from the input directory, returning the ordered list
+/// of file names (the basename of each listed path) it names.
+fn read_manifest(inpath: &Path) -> Vec<String> {
+    fs::read_to_string(inpath.join(MANIFEST_FILE_NAME))
+        .map(|content| {
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .filter_map(|line| Path::new(line).file_name()?.to_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Orders `docs` per `sort`, extending the plain lexical default (`Name`)
+/// with numeric-aware (`Natural`), modification-time (`Mtime`), and
+/// manifest-driven (`Manifest`) alternatives.
+fn sort_documents(
+    mut docs: Vec<(Document, String, SystemTime)>,
+    sort: &SortOrder,
+    inpath: &Path,
+) -> Vec<(Document, String, SystemTime)> {
+    match sort {
+        SortOrder::Name => docs.sort_by(|(_, a, _), (_, b, _)| a.cmp(b)),
+        SortOrder::Natural => docs.sort_by(|(_, a, _), (_, b, _)| natural_cmp(a, b)),
+        SortOrder::Mtime => docs.sort_by_key(|(_, _, mtime)| *mtime),
+        SortOrder::Manifest => {
+            let manifest = read_manifest(inpath);
+            docs.sort_by(|(_, a, _), (_, b, _)| {
+                match (manifest.iter().position(|p| p == a), manifest.iter().position(|p| p == b)) {
+                    (Some(i), Some(j)) => i.cmp(&j),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => natural_cmp(a, b),
+                }
+            });
+        }
+    }
+    docs
+}
+
+// Offsets (in PDF points) from the bottom-left corner of a page's MediaBox
+// at which the file name annotation is placed.
+const ANNO_MARGIN_X: f32 = 10.0;
+const ANNO_MARGIN_Y: f32 = 10.0;
+const ANNO_FONT_SIZE: i64 = 10;
+
+/// Builds the Type1 Helvetica font dictionary used by the `--anno` annotation.
+fn helvetica_font_dict() -> Object {
+    Object::Dictionary(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+    })
+}
+
+/// Reads a page's `MediaBox` (falling back to US Letter) and returns the
+/// bottom-left point offset by the annotation margins.
+fn anno_origin(doc: &Document, page_dict: &lopdf::Dictionary) -> (f32, f32) {
+    let media_box: Vec<Object> = page_dict
+        .get(b"MediaBox")
+        .and_then(|obj| doc.dereference(obj))
+        .and_then(|(_, obj)| obj.as_array().cloned())
+        .unwrap_or_else(|_| vec![0.into(), 0.into(), 612.into(), 792.into()]);
+
+    let llx = media_box.first().and_then(|o| o.as_float().ok()).unwrap_or(0.0);
+    let lly = media_box.get(1).and_then(|o| o.as_float().ok()).unwrap_or(0.0);
+
+    (llx + ANNO_MARGIN_X, lly + ANNO_MARGIN_Y)
+}
+
+/// Stamps `file_name` into the bottom-left corner of `page_id`'s content,
+/// registering a Helvetica font resource as `F1` (replacing any existing `F1`).
+fn annotate_first_page(document: &mut Document, page_id: ObjectId, file_name: &str) {
+    let (x, y) = {
+        let page_dict = match document.get_object(page_id).and_then(|o| o.as_dict()) {
+            Ok(dict) => dict.clone(),
+            Err(_) => return,
+        };
+        anno_origin(document, &page_dict)
+    };
+
+    let content = Content {
+        operations: vec![
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec!["F1".into(), ANNO_FONT_SIZE.into()]),
+            Operation::new("Td", vec![x.into(), y.into()]),
+            Operation::new("Tj", vec![Object::string_literal(file_name)]),
+            Operation::new("ET", vec![]),
+        ],
+    };
+    let content_data = match content.encode() {
+        Ok(data) => data,
+        Err(_) => return,
+    };
+    let content_stream_id = document.add_object(Stream::new(dictionary! {}, content_data));
+
+    let font_id = document.add_object(helvetica_font_dict());
+
+    if let Ok(Object::Dictionary(ref mut page_dict)) = document.get_object_mut(page_id) {
+        let mut contents = match page_dict.get(b"Contents") {
+            Ok(Object::Array(arr)) => arr.clone(),
+            Ok(Object::Reference(id)) => vec![Object::Reference(*id)],
+            _ => vec![],
+        };
+        contents.push(Object::Reference(content_stream_id));
+        page_dict.set("Contents", contents);
+
+        let mut resources = match page_dict.get(b"Resources") {
+            Ok(Object::Dictionary(dict)) => dict.clone(),
+            _ => lopdf::Dictionary::new(),
+        };
+        let mut fonts = match resources.get(b"Font") {
+            Ok(Object::Dictionary(dict)) => dict.clone(),
+            _ => lopdf::Dictionary::new(),
+        };
+        fonts.set("F1", Object::Reference(font_id));
+        resources.set("Font", Object::Dictionary(fonts));
+        page_dict.set("Resources", Object::Dictionary(resources));
+    }
+}
+
+/// A single entry in a source document's outline (bookmark) tree, carrying
+/// the page it targets and its nested children, in source-document object ids.
+struct OutlineNode {
+    title: String,
+    page_id: Option<ObjectId>,
+    children: Vec<OutlineNode>,
+}
+
+/// Resolves an outline item's target page object id from either its `Dest`
+/// entry or a `GoTo` `A` action's `D` entry. Named destinations and any
+/// other action types are not supported and resolve to `None`.
+fn outline_dest_page(doc: &Document, item: &lopdf::Dictionary) -> Option<ObjectId> {
+    let dest_array = if let Ok(dest) = item.get(b"Dest") {
+        doc.dereference(dest).ok().and_then(|(_, o)| o.as_array().ok().cloned())
+    } else if let Ok(action) = item.get(b"A") {
+        doc.dereference(action)
+            .ok()
+            .and_then(|(_, o)| o.as_dict().ok().cloned())
+            .and_then(|action| action.get(b"D").ok().cloned())
+            .and_then(|d| doc.dereference(&d).ok().and_then(|(_, o)| o.as_array().ok().cloned()))
+    } else {
+        None
+    }?;
+
+    dest_array.first().and_then(|o| o.as_reference().ok())
+}
+
+/// Walks the `First`/`Next` chain of outline items starting at `first_ref`,
+/// recursing into each item's own `First` child chain.
+fn outline_items_from(doc: &Document, first_ref: Option<ObjectId>) -> Vec<OutlineNode> {
+    let mut items = vec![];
+    let mut current = first_ref;
+
+    while let Some(id) = current {
+        let Ok(item) = doc.get_object(id).and_then(|o| o.as_dict()) else {
+            break;
+        };
+
+        let title = match item.get(b"Title") {
+            Ok(Object::String(bytes, _)) => String::from_utf8_lossy(bytes).into_owned(),
+            _ => String::new(),
+        };
+        let page_id = outline_dest_page(doc, item);
+        let first_child = item.get(b"First").ok().and_then(|o| o.as_reference().ok());
+        let children = outline_items_from(doc, first_child);
+
+        current = item.get(b"Next").ok().and_then(|o| o.as_reference().ok());
+        items.push(OutlineNode { title, page_id, children });
+    }
+
+    items
+}
+
+/// Extracts `doc`'s outline tree, reached from its Catalog's `Outlines`
+/// entry. Returns an empty tree if the document has no outline.
+fn document_outline(doc: &Document) -> Vec<OutlineNode> {
+    let root = doc
+        .trailer
+        .get(b"Root")
+        .ok()
+        .and_then(|o| o.as_reference().ok())
+        .and_then(|id| doc.get_object(id).ok())
+        .and_then(|o| o.as_dict().ok());
+    let Some(root) = root else {
+        return vec![];
+    };
+
+    let outlines = root
+        .get(b"Outlines")
+        .ok()
+        .and_then(|o| o.as_reference().ok())
+        .and_then(|id| doc.get_object(id).ok())
+        .and_then(|o| o.as_dict().ok());
+    let Some(outlines) = outlines else {
+        return vec![];
+    };
+
+    let first_child = outlines.get(b"First").ok().and_then(|o| o.as_reference().ok());
+    outline_items_from(doc, first_child)
+}
+
+/// Builds the old-id -> new-id map for a renumbering, by matching up page
+/// objects by page number rather than predicting `renumber_objects_with`'s
+/// internal id assignment (which may reorder pages before numbering them).
+fn page_id_map(pages_before: &BTreeMap<u32, ObjectId>, pages_after: &BTreeMap<u32, ObjectId>) -> BTreeMap<ObjectId, ObjectId> {
+    pages_before
+        .iter()
+        .filter_map(|(page_num, &old_id)| pages_after.get(page_num).map(|&new_id| (old_id, new_id)))
+        .collect()
+}
+
+/// Rewrites every `page_id` in an outline tree through `map`, dropping
+/// destinations whose target object was not carried over.
+fn translate_outline(items: Vec<OutlineNode>, map: &BTreeMap<ObjectId, ObjectId>) -> Vec<OutlineNode> {
+    items
+        .into_iter()
+        .map(|item| OutlineNode {
+            page_id: item.page_id.and_then(|id| map.get(&id).copied()),
+            children: translate_outline(item.children, map),
+            ..item
+        })
+        .collect()
+}
+
+/// Adds `items` as `Bookmark`s under `parent`, recursing depth-first so
+/// each item's children are nested under the bookmark it just produced.
+/// Items with no resolvable destination point at page `(0, 0)`, which
+/// `Document::adjust_zero_pages` later replaces with their first child's page.
+fn add_outline_bookmarks(document: &mut Document, items: &[OutlineNode], parent: Option<u32>) {
+    for item in items {
+        let bookmark = Bookmark::new(item.title.clone(), [0.0, 0.0, 1.0], 0, item.page_id.unwrap_or((0, 0)));
+        let bookmark_id = document.add_bookmark(bookmark, parent);
+        add_outline_bookmarks(document, &item.children, Some(bookmark_id));
+    }
+}
+
+// Layout constants for the synthesized `--toc` page.
+const TOC_PAGE_WIDTH: f32 = 612.0;
+const TOC_PAGE_HEIGHT: f32 = 792.0;
+const TOC_MARGIN_X: f32 = 50.0;
+const TOC_TOP_Y: f32 = TOC_PAGE_HEIGHT - 72.0;
+const TOC_LINE_HEIGHT: f32 = 20.0;
+const TOC_FONT_SIZE: i64 = 12;
+
+/// Builds a standalone TOC page listing `entries` (file name, target first
+/// page, starting page number), with one clickable Link annotation per row,
+/// and returns its object id.
+fn build_toc_page(document: &mut Document, pages_id: ObjectId, entries: &[(String, ObjectId, u32)]) -> ObjectId {
+    let mut operations = vec![Operation::new("BT", vec![]), Operation::new("Tf", vec!["F1".into(), TOC_FONT_SIZE.into()])];
+    let mut annots = vec![];
+
+    for (i, (file_name, target_page_id, page_num)) in entries.iter().enumerate() {
+        let y = TOC_TOP_Y - i as f32 * TOC_LINE_HEIGHT;
+
+        if i == 0 {
+            operations.push(Operation::new("Td", vec![TOC_MARGIN_X.into(), y.into()]));
+        } else {
+            operations.push(Operation::new("Td", vec![0.into(), (-TOC_LINE_HEIGHT).into()]));
+        }
+        let label = format!("{}  ....  p.{}", file_name, page_num);
+        operations.push(Operation::new("Tj", vec![Object::string_literal(label)]));
+
+        let rect = vec![
+            TOC_MARGIN_X.into(),
+            (y - 4.0).into(),
+            (TOC_PAGE_WIDTH - TOC_MARGIN_X).into(),
+            (y + TOC_LINE_HEIGHT - 4.0).into(),
+        ];
+        let link = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => rect,
+            "Border" => vec![0.into(), 0.into(), 0.into()],
+            "Dest" => vec![Object::Reference(*target_page_id), "Fit".into()],
+        };
+        annots.push(Object::Reference(document.add_object(Object::Dictionary(link))));
+    }
+    operations.push(Operation::new("ET", vec![]));
+
+    let content_data = Content { operations }.encode().unwrap_or_default();
+    let content_id = document.add_object(Stream::new(dictionary! {}, content_data));
+    let font_id = document.add_object(helvetica_font_dict());
+
+    let page_dict = dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => vec![0.into(), 0.into(), TOC_PAGE_WIDTH.into(), TOC_PAGE_HEIGHT.into()],
+        "Contents" => Object::Reference(content_id),
+        "Resources" => dictionary! { "Font" => dictionary! { "F1" => Object::Reference(font_id) } },
+        "Annots" => annots,
+    };
+
+    document.add_object(Object::Dictionary(page_dict))
+}
+
+/// Reads a page's `MediaBox`, dereferencing it if it's an indirect object.
+fn page_media_box(doc: &Document, page_id: ObjectId) -> Option<Vec<Object>> {
+    doc.get_object(page_id)
+        .ok()?
+        .as_dict()
+        .ok()?
+        .get(b"MediaBox")
+        .ok()
+        .and_then(|obj| doc.dereference(obj).ok())
+        .and_then(|(_, obj)| obj.as_array().ok().cloned())
+}
+
+/// Builds a blank `Page` dictionary for `--duplex` padding, sized to
+/// `media_box` (falling back to US Letter). `Parent` is left unset; the
+/// final pass over `documents_pages` fills it in along with every other page.
+fn blank_page(media_box: Option<Vec<Object>>) -> Object {
+    let media_box = media_box.unwrap_or_else(|| vec![0.into(), 0.into(), 612.into(), 792.into()]);
+    Object::Dictionary(dictionary! {
+        "Type" => "Page",
+        "MediaBox" => media_box,
+        "Contents" => Vec::<Object>::new(),
+    })
+}
+
 // code in merge stolen from library examples heheheha
-fn merge(docs_with_names: Vec<(Document, String)>) -> Result<Document, &'static str> {
+fn merge(
+    docs_with_names: Vec<(Document, String)>,
+    anno: bool,
+    outlines: bool,
+    toc: bool,
+    page_ranges: &HashMap<String, BTreeSet<usize>>,
+    duplex: bool,
+) -> Result<Document, &'static str> {
     // Define a starting `max_id` (will be used as start index for object_ids).
     let mut max_id = 1;
     let mut pagenum = 1;
@@ -77,27 +528,77 @@ fn merge(docs_with_names: Vec<(Document, String)>) -> Result<Document, &'static
     let mut documents_pages = BTreeMap::new();
     let mut documents_objects = BTreeMap::new();
     let mut document = Document::with_version("1.5");
+    let mut annotations: Vec<(ObjectId, String)> = vec![];
+    let mut toc_entries: Vec<(String, ObjectId, u32)> = vec![];
+    // Running count of real (non-TOC) pages placed by prior sections, used to
+    // compute each section's true starting page number for the TOC.
+    let mut page_offset: u32 = 0;
 
-    for (mut doc, _) in docs_with_names {
+    for (mut doc, file_name) in docs_with_names {
         let mut first = false;
+
+        let raw_outline = if outlines { document_outline(&doc) } else { vec![] };
+        let pages_before = if outlines { doc.get_pages() } else { BTreeMap::new() };
+
         doc.renumber_objects_with(max_id);
 
         max_id = doc.max_id + 1;
 
+        let translated_outline = if outlines {
+            let map = page_id_map(&pages_before, &doc.get_pages());
+            translate_outline(raw_outline, &map)
+        } else {
+            vec![]
+        };
+
+        let selected_pages = page_ranges.get(&file_name);
+        let section_pages: Vec<(u32, ObjectId)> = doc
+            .get_pages()
+            .into_iter()
+            .filter(|(page_num, _)| match selected_pages {
+                Some(pages) => pages.contains(&(*page_num as usize)),
+                None => true,
+            })
+            .collect();
+
+        let last_page_media_box = duplex
+            .then(|| section_pages.last())
+            .flatten()
+            .and_then(|&(_, object_id)| page_media_box(&doc, object_id));
+        let section_page_count = section_pages.len() as u32;
+        let odd_section = section_page_count % 2 == 1;
+        // The TOC page (if any) is spliced in ahead of every real page.
+        let section_start_page = page_offset + 1 + if toc { 1 } else { 0 };
+
         documents_pages.extend(
-            doc.get_pages()
+            section_pages
                 .into_iter()
                 .map(|(_, object_id)| {
                     if !first {
-                        let bookmark = Bookmark::new(
-                            String::from(format!("Page_{}", pagenum)),
-                            [0.0, 0.0, 1.0],
-                            0,
-                            object_id,
-                        );
-                        document.add_bookmark(bookmark, None);
+                        if !translated_outline.is_empty() {
+                            let parent_bookmark =
+                                Bookmark::new(file_name.clone(), [0.0, 0.0, 1.0], 0, object_id);
+                            let parent_id = document.add_bookmark(parent_bookmark, None);
+                            add_outline_bookmarks(&mut document, &translated_outline, Some(parent_id));
+                        } else {
+                            let bookmark = Bookmark::new(
+                                String::from(format!("Page_{}", pagenum)),
+                                [0.0, 0.0, 1.0],
+                                0,
+                                object_id,
+                            );
+                            document.add_bookmark(bookmark, None);
+                        }
+                        if toc {
+                            toc_entries.push((file_name.clone(), object_id, section_start_page));
+                        }
+
                         first = true;
                         pagenum += 1;
+
+                        if anno {
+                            annotations.push((object_id, file_name.clone()));
+                        }
                     }
 
                     (object_id, doc.get_object(object_id).unwrap().to_owned())
@@ -105,8 +606,24 @@ fn merge(docs_with_names: Vec<(Document, String)>) -> Result<Document, &'static
                 .collect::<BTreeMap<ObjectId, Object>>(),
         );
         documents_objects.extend(doc.objects);
+
+        page_offset += section_page_count;
+
+        if duplex && odd_section {
+            let blank_id = (max_id, 0);
+            max_id += 1;
+            documents_pages.insert(blank_id, blank_page(last_page_media_box));
+            page_offset += 1;
+        }
     }
 
+    // `max_id` already tracks one past the highest object id used by any
+    // source document. Objects added below via `document.add_object` (the
+    // `--anno` stamp and the `--toc` page) rely on `document.max_id` to avoid
+    // colliding with those ids, but `document` was built via direct
+    // `objects.insert` calls, which never bump it.
+    document.max_id = max_id - 1;
+
     // "Catalog" and "Pages" are mandatory.
     let mut catalog_object: Option<(ObjectId, Object)> = None;
     let mut pages_object: Option<(ObjectId, Object)> = None;
@@ -174,6 +691,11 @@ fn merge(docs_with_names: Vec<(Document, String)>) -> Result<Document, &'static
         }
     }
 
+    // Stamp each section's first page with its source file name.
+    for (page_id, file_name) in annotations {
+        annotate_first_page(&mut document, page_id, &file_name);
+    }
+
     // If no "Catalog" found, abort.
     if catalog_object.is_none() {
         return Err("Catalog root not found.");
@@ -216,6 +738,24 @@ fn merge(docs_with_names: Vec<(Document, String)>) -> Result<Document, &'static
 
     document.trailer.set("Root", catalog_object.0);
 
+    // Synthesize a TOC page, linking each entry to its document's first page,
+    // and splice it in as the new first page of the merged document.
+    if toc && !toc_entries.is_empty() {
+        let toc_page_id = build_toc_page(&mut document, pages_object.0, &toc_entries);
+
+        if let Ok(Object::Dictionary(ref mut pages_dict)) = document.get_object_mut(pages_object.0) {
+            let mut kids = match pages_dict.get(b"Kids") {
+                Ok(Object::Array(arr)) => arr.clone(),
+                _ => vec![],
+            };
+            kids.insert(0, Object::Reference(toc_page_id));
+            pages_dict.set("Kids", kids);
+
+            let count = pages_dict.get(b"Count").and_then(|o| o.as_i64()).unwrap_or(0);
+            pages_dict.set("Count", count + 1);
+        }
+    }
+
     // Update the max internal ID as wasn't updated before due to direct objects insertion
     document.max_id = document.objects.len() as u32;
 
@@ -244,19 +784,23 @@ fn main() {
     let inpath = PathBuf::from(args.inpath);
     let outpath = PathBuf::from(args.outpath);
     println!("Path:\n    {:?}", inpath);
-    let mut docs: Vec<(Document, String)> = load_documents_from_path(&inpath);
+    let docs = load_documents_from_path(&inpath);
     if docs.len() == 0 {
         println!("No PDFs found");
         return;
     }
-    docs.sort_by(|(_, a), (_, b)| a.cmp(b));
+    let docs: Vec<(Document, String)> = sort_documents(docs, &args.sort, &inpath)
+        .into_iter()
+        .map(|(doc, name, _)| (doc, name))
+        .collect();
     println!("Order:");
     for (doc, name) in &docs {
         println!("    Title: {}, Pages: {}", name, doc.get_pages().len());
     }
 
     let merged_file_path = Path::new(&outpath);
-    let merged_doc = merge(docs);
+    let page_ranges = parse_page_ranges(&args.range);
+    let merged_doc = merge(docs, args.anno, args.outlines, args.toc, &page_ranges, args.duplex);
     match merged_doc {
         Ok(mut merged_doc) => {
             merged_doc.save(merged_file_path).unwrap();